@@ -1,5 +1,6 @@
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::sync::Mutex;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 enum HuffmanNode {
@@ -8,7 +9,7 @@ enum HuffmanNode {
         right: Box<HuffmanNode>,
     },
     Leaf {
-        character: char,
+        symbol: u16,
         frequency: usize,
     },
 }
@@ -34,14 +35,11 @@ impl PartialOrd for HuffmanNode {
     }
 }
 
-fn build_tree(frequencies: &HashMap<char, usize>) -> HuffmanNode {
+fn build_tree(frequencies: &HashMap<u16, usize>) -> HuffmanNode {
     let mut heap = BinaryHeap::new();
 
-    for (character, &frequency) in frequencies {
-        heap.push(HuffmanNode::Leaf {
-            character: *character,
-            frequency,
-        });
+    for (&symbol, &frequency) in frequencies {
+        heap.push(HuffmanNode::Leaf { symbol, frequency });
     }
 
     while heap.len() > 1 {
@@ -57,25 +55,96 @@ fn build_tree(frequencies: &HashMap<char, usize>) -> HuffmanNode {
     heap.pop().unwrap()
 }
 
-fn build_codebook(
-    node: &HuffmanNode,
-    prefix: VecDeque<bool>,
-    codebook: &mut HashMap<char, VecDeque<bool>>,
-) {
+// Canonical Huffman only needs the code *length* per symbol, not the tree
+// shape itself, so this walks the tree recording each leaf's depth instead
+// of building prefix codes directly. A lone-symbol tree (no internal node)
+// is given length 1, since a zero-length code can't be assigned.
+fn code_lengths(node: &HuffmanNode, depth: u8, lengths: &mut Vec<(u16, u8)>) {
     match node {
-        HuffmanNode::Leaf { character, .. } => {
-            codebook.insert(*character, prefix);
-        }
+        HuffmanNode::Leaf { symbol, .. } => lengths.push((*symbol, depth.max(1))),
         HuffmanNode::Internal { left, right } => {
-            let mut left_prefix = prefix.clone();
-            left_prefix.push_back(false);
-            build_codebook(left, left_prefix, codebook);
+            code_lengths(left, depth + 1, lengths);
+            code_lengths(right, depth + 1, lengths);
+        }
+    }
+}
+
+// Codes longer than this don't fit a fixed-width length field, so they get
+// capped.
+const MAX_CODE_LENGTH: u8 = 15;
+
+// Clamps any code length above `max_bits`, then pushes leaves one level
+// deeper (longest length below `max_bits` first) until the Kraft sum of the
+// clamped lengths is back at or under 1, so the canonical code built from
+// them is still a valid prefix code.
+fn limit_lengths(lengths: &mut [(u16, u8)], max_bits: u8) {
+    let max_len = lengths.iter().map(|&(_, len)| len).max().unwrap_or(0);
+    if max_len <= max_bits {
+        return;
+    }
+
+    let mut bit_count = vec![0i64; max_bits as usize + 1];
+    for &(_, len) in lengths.iter() {
+        bit_count[len.min(max_bits) as usize] += 1;
+    }
+
+    // Kraft sum scaled by 2^max_bits, so it's an exact integer: a valid
+    // code needs this at or under `budget`.
+    let budget = 1i64 << max_bits;
+    let kraft_sum = |bit_count: &[i64]| -> i64 {
+        bit_count
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(len, &count)| count << (max_bits as usize - len))
+            .sum()
+    };
 
-            let mut right_prefix = prefix;
-            right_prefix.push_back(true);
-            build_codebook(right, right_prefix, codebook);
+    while kraft_sum(&bit_count) > budget {
+        let mut len = max_bits as usize - 1;
+        while bit_count[len] == 0 {
+            len -= 1;
         }
+        bit_count[len] -= 1;
+        bit_count[len + 1] += 1;
     }
+
+    // Reassign lengths in the same relative order as before, so symbols
+    // that had shorter codes keep shorter codes.
+    lengths.sort_by_key(|&(symbol, len)| (len, symbol));
+    let mut corrected = Vec::with_capacity(lengths.len());
+    for (len, &count) in bit_count.iter().enumerate() {
+        corrected.extend(std::iter::repeat_n(len as u8, count as usize));
+    }
+    for ((_, len), new_len) in lengths.iter_mut().zip(corrected) {
+        *len = new_len;
+    }
+}
+
+// The canonical code for each symbol, and the reverse (length, code) ->
+// symbol table used to decode, both derived purely from the code lengths.
+struct CanonicalCodes {
+    encode: HashMap<u16, (u32, u8)>,
+    decode: HashMap<(u8, u32), u16>,
+}
+
+fn build_canonical_codes(lengths: &[(u16, u8)]) -> CanonicalCodes {
+    let mut sorted = lengths.to_vec();
+    sorted.sort_by_key(|&(symbol, len)| (len, symbol));
+
+    let mut encode = HashMap::new();
+    let mut decode = HashMap::new();
+    let mut code: u32 = 0;
+    let mut prev_len = 0u8;
+    for (symbol, len) in sorted {
+        code <<= len - prev_len;
+        encode.insert(symbol, (code, len));
+        decode.insert((len, code), symbol);
+        code += 1;
+        prev_len = len;
+    }
+
+    CanonicalCodes { encode, decode }
 }
 
 const MIN_MATCH: usize = 2;
@@ -89,48 +158,139 @@ struct Encoded {
     byte: u8,
 }
 
-fn find_longest_match(data: &[u8], cur: usize) -> (usize, usize) {
-    let mut max_len = 0;
-    let mut match_index = 0;
+// Tuning knobs for the LZ77 match finder, trading search effort for ratio.
+#[derive(Debug, Clone, Copy)]
+struct Lz77Options {
+    // How many candidates to walk down a hash chain before settling for the
+    // best match found so far.
+    probe_max: usize,
+    // Whether to check if the match one byte further along is longer before
+    // committing to the match at the current position.
+    lazy_match: bool,
+}
+
+impl Default for Lz77Options {
+    fn default() -> Self {
+        Lz77Options {
+            probe_max: 32,
+            lazy_match: true,
+        }
+    }
+}
+
+// Bucket count for the 3-byte rolling hash; a power of two keeps the mask
+// cheap.
+const HASH_BITS: u32 = 12;
+const HASH_SIZE: usize = 1 << HASH_BITS;
+// Sentinel meaning "no entry", since 0 is a valid position.
+const NO_MATCH: usize = usize::MAX;
+
+fn hash3(data: &[u8], i: usize) -> usize {
+    let hash = (data[i] as u32) ^ ((data[i + 1] as u32) << 5) ^ ((data[i + 2] as u32) << 10);
+    (hash as usize) & (HASH_SIZE - 1)
+}
 
-    // Start at the beginning of the window (max(0, cur - WINDOW_SIZE))
-    let mut start = cur.saturating_sub(WINDOW_SIZE);
+// head[hash] is the most recent position with that 3-byte prefix hash;
+// prev[pos] links back to the previous position sharing it.
+struct MatchFinder {
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl MatchFinder {
+    fn new(len: usize) -> Self {
+        MatchFinder {
+            head: vec![NO_MATCH; HASH_SIZE],
+            prev: vec![NO_MATCH; len],
+        }
+    }
 
-    while start < cur {
-        // start から始まる文字列と現在位置(cur)から始まる文字列の最長一致を探す
-        let mut reference_match_index = start;
-        let mut current_match_index = cur;
+    fn insert(&mut self, data: &[u8], pos: usize) {
+        if pos + 3 > data.len() {
+            return;
+        }
+        // The streaming `Compressor` doesn't know the final input length up
+        // front, so it builds its finder with an empty chain and lets this
+        // grow on demand instead of pre-sizing `prev`.
+        if pos >= self.prev.len() {
+            self.prev.resize(pos + 1, NO_MATCH);
+        }
+        let hash = hash3(data, pos);
+        self.prev[pos] = self.head[hash];
+        self.head[hash] = pos;
+    }
 
-        // 一致する文字列を探す
-        // 一致する文字列の長さが WINDOW_SIZE を超えないようにする
-        while current_match_index < data.len()
-            && data[reference_match_index] == data[current_match_index]
-            && (current_match_index - cur) < WINDOW_SIZE
-        {
-            reference_match_index += 1;
-            current_match_index += 1;
+    fn find_longest_match(&self, data: &[u8], cur: usize, probe_max: usize) -> (usize, usize) {
+        if cur + 3 > data.len() {
+            return (0, 0);
         }
 
-        // 一致する文字列の長さを計算する
-        let len = reference_match_index - start;
-        if len > max_len {
-            max_len = len;
-            match_index = start;
+        let window_start = cur.saturating_sub(WINDOW_SIZE);
+        let mut max_len = 0;
+        let mut match_index = cur;
+        let mut candidate = self.head[hash3(data, cur)];
+        let mut probes = 0;
+
+        while candidate != NO_MATCH && candidate >= window_start && probes < probe_max {
+            let mut len = 0;
+            while cur + len < data.len() && len < WINDOW_SIZE && data[candidate + len] == data[cur + len] {
+                len += 1;
+            }
+            if len > max_len {
+                max_len = len;
+                match_index = candidate;
+            }
+
+            candidate = self.prev[candidate];
+            probes += 1;
         }
 
-        start += 1;
+        (cur - match_index, max_len)
     }
 
-    // 相対位置と一致する文字列の長さを返す
-    (cur - match_index, max_len)
+    // Drops the first `amount` positions from the chain: entries pointing
+    // into that range no longer have data backing them and become NO_MATCH,
+    // everything else shifts down by `amount` to match `data` being
+    // truncated the same way. Lets the streaming `Compressor` discard bytes
+    // it no longer needs instead of retaining the whole stream.
+    fn shift(&mut self, amount: usize) {
+        let remap = |pos: usize| {
+            if pos == NO_MATCH || pos < amount {
+                NO_MATCH
+            } else {
+                pos - amount
+            }
+        };
+        for head in self.head.iter_mut() {
+            *head = remap(*head);
+        }
+        let mut shifted = vec![NO_MATCH; self.prev.len().saturating_sub(amount)];
+        for (i, new_pos) in shifted.iter_mut().enumerate() {
+            *new_pos = remap(self.prev[i + amount]);
+        }
+        self.prev = shifted;
+    }
 }
 
-fn lz77_encode(data: &[u8]) -> Vec<Encoded> {
+// Inserts every position strictly before `pos` that isn't already in the
+// chain. Matching must always probe a position before inserting it, or it
+// would find itself as a zero-offset "match".
+fn insert_before(finder: &mut MatchFinder, data: &[u8], inserted: &mut usize, pos: usize) {
+    while *inserted < pos {
+        finder.insert(data, *inserted);
+        *inserted += 1;
+    }
+}
+
+fn lz77_encode(data: &[u8], options: Lz77Options) -> Vec<Encoded> {
     let mut compressed = Vec::new();
+    let mut finder = MatchFinder::new(data.len());
+    let mut inserted = 0;
     let mut i = 0;
 
     while i < data.len() {
-        let (offset, length) = find_longest_match(data, i);
+        insert_before(&mut finder, data, &mut inserted, i);
+        let (offset, length) = finder.find_longest_match(data, i, options.probe_max);
 
         // MIN_MATCH より短い一致は圧縮しない
         if length < MIN_MATCH {
@@ -140,28 +300,186 @@ fn lz77_encode(data: &[u8]) -> Vec<Encoded> {
                 byte: data[i],
             });
             i += 1;
-        } else {
-            compressed.push(Encoded {
-                offset,
-                length,
-                byte: 0,
-            });
-            i += length;
+            continue;
+        }
+
+        insert_before(&mut finder, data, &mut inserted, i + 1);
+
+        if options.lazy_match && i + 1 < data.len() {
+            let (_, next_length) = finder.find_longest_match(data, i + 1, options.probe_max);
+            if next_length > length {
+                // The match starting one byte later is better: emit a
+                // literal here and let the next iteration take it instead.
+                compressed.push(Encoded {
+                    offset: 0,
+                    length: 0,
+                    byte: data[i],
+                });
+                i += 1;
+                continue;
+            }
         }
+
+        insert_before(&mut finder, data, &mut inserted, i + length);
+        compressed.push(Encoded {
+            offset,
+            length,
+            byte: 0,
+        });
+        i += length;
     }
 
     compressed
 }
 
-fn lz77_decode(compressed: &[Encoded]) -> Vec<u8> {
+// Packs individual bits into bytes, most-significant bit first.
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u8,
+    filled: u8,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            bytes: Vec::new(),
+            current: 0,
+            filled: 0,
+        }
+    }
+
+    fn write_bit(&mut self, bit: bool) {
+        self.current = (self.current << 1) | bit as u8;
+        self.filled += 1;
+        if self.filled == 8 {
+            self.bytes.push(self.current);
+            self.current = 0;
+            self.filled = 0;
+        }
+    }
+
+    fn write_bits(&mut self, value: usize, count: u8) {
+        for i in (0..count).rev() {
+            self.write_bit((value >> i) & 1 == 1);
+        }
+    }
+
+    // Pads the final partial byte with zero bits.
+    fn finish(mut self) -> Vec<u8> {
+        if self.filled > 0 {
+            self.current <<= 8 - self.filled;
+            self.bytes.push(self.current);
+        }
+        self.bytes
+    }
+}
+
+// Reads bits back out in the same order BitWriter wrote them in.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    byte_pos: usize,
+    bit_pos: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        BitReader {
+            bytes,
+            byte_pos: 0,
+            bit_pos: 0,
+        }
+    }
+
+    fn read_bit(&mut self) -> bool {
+        let byte = self.bytes.get(self.byte_pos).copied().unwrap_or(0);
+        let bit = (byte >> (7 - self.bit_pos)) & 1 == 1;
+        self.bit_pos += 1;
+        if self.bit_pos == 8 {
+            self.bit_pos = 0;
+            self.byte_pos += 1;
+        }
+        bit
+    }
+
+    fn read_bits(&mut self, count: u8) -> usize {
+        let mut value = 0usize;
+        for _ in 0..count {
+            value = (value << 1) | self.read_bit() as usize;
+        }
+        value
+    }
+}
+
+// Literal/length alphabet: symbols 0-255 are literal bytes, 256 marks the
+// end of the block, and 257+ are length codes.
+const END_OF_BLOCK: u16 = 256;
+const LENGTH_SYMBOL_BASE: u16 = 257;
+// Distance codes (see `bucket`) top out at 7 for WINDOW_SIZE = 255, so 3
+// bits is always enough to hold one.
+const DIST_CODE_BITS: u8 = 3;
+
+// Splits `value` (>= 1) into a power-of-two bucket: `base` is the largest
+// power of two <= `value`, and `extra_bits` is how many more bits are
+// needed to encode `value - base`.
+fn bucket(value: usize) -> (u8, usize) {
+    let extra_bits = (usize::BITS - 1 - value.leading_zeros()) as u8;
+    (extra_bits, 1usize << extra_bits)
+}
+
+// Literal/length symbols from an LZ77 token sequence, plus the match
+// lengths/distances packed separately as base-plus-extra-bits (see
+// `bucket`). Decoding walks the symbols in order and pulls extra bits
+// whenever a length-code symbol is seen.
+struct TokenStream {
+    symbols: Vec<u16>,
+    extra_bits: Vec<u8>,
+}
+
+fn build_token_stream(lz77_encoded: &[Encoded]) -> TokenStream {
+    let mut symbols = Vec::with_capacity(lz77_encoded.len() + 1);
+    let mut extra = BitWriter::new();
+
+    for encoded in lz77_encoded {
+        if encoded.length == 0 {
+            symbols.push(encoded.byte as u16);
+        } else {
+            let (length_extra_bits, length_base) = bucket(encoded.length);
+            extra.write_bits(encoded.length - length_base, length_extra_bits);
+
+            let (dist_code, dist_base) = bucket(encoded.offset);
+            extra.write_bits(dist_code as usize, DIST_CODE_BITS);
+            extra.write_bits(encoded.offset - dist_base, dist_code);
+
+            symbols.push(LENGTH_SYMBOL_BASE + length_extra_bits as u16);
+        }
+    }
+    symbols.push(END_OF_BLOCK);
+
+    TokenStream {
+        symbols,
+        extra_bits: extra.finish(),
+    }
+}
+
+// Reassembles LZ77 output from a decoded symbol stream and its extra bits.
+fn decode_token_stream(symbols: &[u16], extra_bits: &[u8]) -> Vec<u8> {
+    let mut extra = BitReader::new(extra_bits);
     let mut decompressed = Vec::new();
 
-    for enc in compressed.iter() {
-        if enc.length == 0 {
-            decompressed.push(enc.byte);
+    for &symbol in symbols {
+        if symbol == END_OF_BLOCK {
+            break;
+        } else if symbol < LENGTH_SYMBOL_BASE {
+            decompressed.push(symbol as u8);
         } else {
-            let start = decompressed.len() - enc.offset;
-            for i in start..start + enc.length {
+            let length_extra_bits = (symbol - LENGTH_SYMBOL_BASE) as u8;
+            let length = (1usize << length_extra_bits) + extra.read_bits(length_extra_bits);
+
+            let dist_code = extra.read_bits(DIST_CODE_BITS) as u8;
+            let offset = (1usize << dist_code) + extra.read_bits(dist_code);
+
+            let start = decompressed.len() - offset;
+            for i in start..start + length {
                 decompressed.push(decompressed[i]);
             }
         }
@@ -170,109 +488,1104 @@ fn lz77_decode(compressed: &[Encoded]) -> Vec<u8> {
     decompressed
 }
 
-fn zip_compress(data: &[u8]) -> (VecDeque<bool>, HashMap<char, VecDeque<bool>>) {
-    let lz77_encoded = lz77_encode(data);
+// Adaptive binary range coder, an alternative entropy stage to Huffman - no
+// codebook needed since encoder and decoder adapt the same probabilities.
+mod rangecoder {
+    // Renormalize whenever the range drops below this threshold.
+    const TOP: u32 = 1 << 24;
+    // Probability counts are nudged by this much per observed bit...
+    const INC: u32 = 4;
+    // ...and halved once their sum reaches this, so the model keeps
+    // adapting instead of freezing once a symbol has been seen many times.
+    const LIMIT: u32 = 0x200;
 
-    let mut lz77_string = String::new();
-    for encoded in &lz77_encoded {
-        lz77_string.push_str(&format!(
-            "{:02}{:02}{}",
-            encoded.offset, encoded.length, encoded.byte as char
-        ));
+    // c0/c1 are running counts of the 0/1 bits seen so far, both kept above zero.
+    #[derive(Debug, Clone, Copy)]
+    pub struct Context {
+        c0: u32,
+        c1: u32,
     }
-    println!("lz77_string: {}", lz77_string);
 
-    // frequency for huffman tree
-    let mut frequencies: HashMap<char, usize> = HashMap::new();
-    for ch in lz77_string.chars() {
-        let counter = frequencies.entry(ch).or_insert(0);
-        *counter += 1;
+    impl Default for Context {
+        fn default() -> Self {
+            Context { c0: 1, c1: 1 }
+        }
     }
 
-    // huffman tree
-    let tree = build_tree(&frequencies);
+    impl Context {
+        fn update(&mut self, bit: bool) {
+            if bit {
+                self.c1 += INC;
+            } else {
+                self.c0 += INC;
+            }
+            if self.c0 + self.c1 >= LIMIT {
+                self.c0 = (self.c0 >> 1) | 1;
+                self.c1 = (self.c1 >> 1) | 1;
+            }
+        }
+    }
 
-    // codebook
-    let mut codebook = HashMap::new();
-    build_codebook(&tree, VecDeque::new(), &mut codebook);
-
-    // huffman encode
-    let mut huffman_encoded = VecDeque::new();
-    for ch in lz77_string.chars() {
-        let code = codebook.get(&ch).expect("Character not in codebook");
-        huffman_encoded.extend(code.clone());
-    }
-    println!("codebook: {:?}", codebook);
-
-    (huffman_encoded, codebook)
-}
-
-fn zip_decompress(data: &VecDeque<bool>, codebook: &HashMap<char, VecDeque<bool>>) -> Vec<u8> {
-    // huffman decode
-    let lz77_string = huffman_decode(data, codebook);
-    println!("lz77_string: {}", lz77_string);
-
-    // lz77 decode
-    let mut lz77_encoded = Vec::new();
-    let mut chars = lz77_string.chars();
-    while let Some(offset_char) = chars.next() {
-        let offset = format!(
-            "{}{}",
-            offset_char,
-            chars.next().expect("Expected another character for offset")
-        )
-        .parse::<usize>()
-        .expect("Failed to parse offset");
-        let length = format!(
-            "{}{}",
-            chars.next().expect("Expected a character for length"),
-            chars.next().expect("Expected another character for length")
-        )
-        .parse::<usize>()
-        .expect("Failed to parse length");
-        let current_byte = chars.next().expect("Expected a character for current_byte") as u8;
-        println!(
-            "offset: {}, length: {}, current_byte: {}",
-            offset, length, current_byte
-        );
-
-        lz77_encoded.push(Encoded {
-            offset,
-            length,
-            byte: current_byte,
-        });
+    // Encodes a sequence of bits, each coded against an adaptive Context.
+    pub struct Encoder {
+        low: u64,
+        range: u32,
+        cache: u8,
+        cache_size: u64,
+        out: Vec<u8>,
+    }
+
+    impl Encoder {
+        pub fn new() -> Self {
+            Encoder {
+                low: 0,
+                range: 0xFFFF_FFFF,
+                cache: 0xFF,
+                cache_size: 1,
+                out: Vec::new(),
+            }
+        }
+
+        pub fn encode_bit(&mut self, ctx: &mut Context, bit: bool) {
+            let temp = self.range / (ctx.c0 + ctx.c1);
+            if bit {
+                self.low += (temp * ctx.c0) as u64;
+                self.range = temp * ctx.c1;
+            } else {
+                self.range = temp * ctx.c0;
+            }
+            ctx.update(bit);
+            self.normalize();
+        }
+
+        fn normalize(&mut self) {
+            while self.range < TOP {
+                self.shift_low();
+                self.range <<= 8;
+            }
+        }
+
+        // Pushes the top byte of `low` to the output, propagating any carry
+        // into bytes that were already emitted but buffered as `cache`/
+        // `cache_size` because they were still `0xFF` and could yet change.
+        fn shift_low(&mut self) {
+            if self.low < 0xFF00_0000 || self.low > 0xFFFF_FFFF {
+                let carry = (self.low >> 32) as u8;
+                self.out.push(self.cache.wrapping_add(carry));
+                for _ in 1..self.cache_size {
+                    self.out.push(0xFFu8.wrapping_add(carry));
+                }
+                self.cache_size = 0;
+                self.cache = (self.low >> 24) as u8;
+            }
+            self.cache_size += 1;
+            self.low = (self.low << 8) & 0xFFFF_FFFF;
+        }
+
+        // Flushes the bits still buffered in `low`; the first output byte is
+        // a placeholder the decoder discards.
+        pub fn finish(mut self) -> Vec<u8> {
+            for _ in 0..5 {
+                self.shift_low();
+            }
+            self.out
+        }
+
+        // Removes and returns whatever coded bytes are ready so far, without
+        // finishing the stream, so encoding can carry on afterwards.
+        pub fn drain_output(&mut self) -> Vec<u8> {
+            std::mem::take(&mut self.out)
+        }
+
+        // Codes `count` bits of `value` at a fixed 50/50 split, with no
+        // Context - for data like extra length/distance bits that doesn't
+        // benefit from a probability model.
+        pub fn encode_bits_bypass(&mut self, value: usize, count: u8) {
+            for i in (0..count).rev() {
+                let bit = (value >> i) & 1 == 1;
+                let half = self.range >> 1;
+                if bit {
+                    self.low += half as u64;
+                    self.range -= half;
+                } else {
+                    self.range = half;
+                }
+                self.normalize();
+            }
+        }
     }
 
-    lz77_decode(&lz77_encoded)
+    // Mirrors Encoder, reading bits back out. Owns a growable buffer
+    // instead of borrowing a fixed slice, since the streaming Decompressor
+    // feeds it chunks as they arrive.
+    pub struct Decoder {
+        range: u32,
+        code: u32,
+        buffer: Vec<u8>,
+        pos: usize,
+        primed: bool,
+    }
+
+    impl Decoder {
+        pub fn new(data: &[u8]) -> Self {
+            let mut decoder = Decoder {
+                range: 0xFFFF_FFFF,
+                code: 0,
+                buffer: Vec::new(),
+                pos: 0,
+                primed: false,
+            };
+            decoder.feed(data);
+            decoder.prime();
+            decoder
+        }
+
+        // Appends more coded bytes for decode_bit/decode_bits_bypass to consume.
+        pub fn feed(&mut self, bytes: &[u8]) {
+            self.buffer.extend_from_slice(bytes);
+        }
+
+        // How many fed bytes haven't been consumed yet.
+        pub fn unread_len(&self) -> usize {
+            self.buffer.len() - self.pos
+        }
+
+        fn next_byte(&mut self) -> u8 {
+            let byte = self.buffer.get(self.pos).copied().unwrap_or(0);
+            self.pos += 1;
+            byte
+        }
+
+        // The first 5 coded bytes (a placeholder plus the initial 4-byte
+        // `code`) have to be consumed before any bit can be decoded; this is
+        // a no-op once that's already happened.
+        fn prime(&mut self) {
+            if self.primed || self.unread_len() < 5 {
+                return;
+            }
+            self.next_byte(); // discard the encoder's placeholder byte
+            let mut code = 0u32;
+            for _ in 0..4 {
+                code = (code << 8) | self.next_byte() as u32;
+            }
+            self.code = code;
+            self.primed = true;
+        }
+
+        pub fn decode_bit(&mut self, ctx: &mut Context) -> bool {
+            self.prime();
+            let temp = self.range / (ctx.c0 + ctx.c1);
+            let bound = temp * ctx.c0;
+            let bit = self.code >= bound;
+            if bit {
+                self.code -= bound;
+                self.range = temp * ctx.c1;
+            } else {
+                self.range = bound;
+            }
+            ctx.update(bit);
+            self.normalize();
+            bit
+        }
+
+        // Mirrors Encoder::encode_bits_bypass.
+        pub fn decode_bits_bypass(&mut self, count: u8) -> usize {
+            let mut value = 0usize;
+            for _ in 0..count {
+                let half = self.range >> 1;
+                let bit = self.code >= half;
+                if bit {
+                    self.code -= half;
+                    self.range -= half;
+                } else {
+                    self.range = half;
+                }
+                self.normalize();
+                value = (value << 1) | bit as usize;
+            }
+            value
+        }
+
+        fn normalize(&mut self) {
+            while self.range < TOP {
+                self.code = (self.code << 8) | self.next_byte() as u32;
+                self.range <<= 8;
+            }
+        }
+    }
+
+    // Codes whole bytes by walking an implicit binary tree of 255 contexts,
+    // one per internal node.
+    pub struct ByteModel {
+        contexts: [Context; 256],
+    }
+
+    impl Default for ByteModel {
+        fn default() -> Self {
+            ByteModel {
+                contexts: [Context::default(); 256],
+            }
+        }
+    }
+
+    impl ByteModel {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn encode_byte(&mut self, encoder: &mut Encoder, byte: u8) {
+            let mut node = 1usize;
+            for i in (0..8).rev() {
+                let bit = (byte >> i) & 1 == 1;
+                encoder.encode_bit(&mut self.contexts[node], bit);
+                node = (node << 1) | bit as usize;
+            }
+        }
+
+        pub fn decode_byte(&mut self, decoder: &mut Decoder) -> u8 {
+            let mut node = 1usize;
+            for _ in 0..8 {
+                let bit = decoder.decode_bit(&mut self.contexts[node]);
+                node = (node << 1) | bit as usize;
+            }
+            (node & 0xFF) as u8
+        }
+    }
+
+    // Like ByteModel, but codes a fixed-width symbol of up to 15 bits
+    // instead of exactly 8.
+    pub struct SymbolModel {
+        bits: u8,
+        contexts: Vec<Context>,
+    }
+
+    impl SymbolModel {
+        pub fn new(bits: u8) -> Self {
+            SymbolModel {
+                bits,
+                contexts: vec![Context::default(); 1 << bits],
+            }
+        }
+
+        pub fn encode(&mut self, encoder: &mut Encoder, value: u16) {
+            let mut node = 1usize;
+            for i in (0..self.bits).rev() {
+                let bit = (value >> i) & 1 == 1;
+                encoder.encode_bit(&mut self.contexts[node], bit);
+                node = (node << 1) | bit as usize;
+            }
+        }
+
+        pub fn decode(&mut self, decoder: &mut Decoder) -> u16 {
+            let mut node = 1usize;
+            for _ in 0..self.bits {
+                let bit = decoder.decode_bit(&mut self.contexts[node]);
+                node = (node << 1) | bit as usize;
+            }
+            (node as u16) & ((1u16 << self.bits) - 1)
+        }
+    }
 }
 
-fn huffman_decode(data: &VecDeque<bool>, codebook: &HashMap<char, VecDeque<bool>>) -> String {
-    let mut decoded = String::new();
-    let mut bits = VecDeque::new();
+fn rangecoder_encode_bytes(data: &[u8]) -> Vec<u8> {
+    let mut model = rangecoder::ByteModel::new();
+    let mut encoder = rangecoder::Encoder::new();
+    for &byte in data {
+        model.encode_byte(&mut encoder, byte);
+    }
+    encoder.finish()
+}
 
-    for bit in data.iter() {
-        bits.push_back(*bit);
-        if let Some(&ch) = codebook
-            .iter()
-            .find(|&(_, value)| value == &bits)
-            .map(|(key, _)| key)
-        {
-            decoded.push(ch);
-            bits.clear();
+fn rangecoder_decode_bytes(data: &[u8], len: usize) -> Vec<u8> {
+    let mut model = rangecoder::ByteModel::new();
+    let mut decoder = rangecoder::Decoder::new(data);
+    (0..len).map(|_| model.decode_byte(&mut decoder)).collect()
+}
+
+// Entropy-coding stage applied after LZ77.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntropyBackend {
+    Huffman,
+    RangeCoder,
+}
+
+// Size of the literal/length alphabet: 256 literal bytes, the end-of-block
+// symbol, and one length-code symbol per possible `bucket` extra-bits value
+// (0..=7, since WINDOW_SIZE = 255 bounds match lengths the same way).
+const ALPHABET_SIZE: usize = LENGTH_SYMBOL_BASE as usize + 8;
+
+enum ZipCompressed {
+    Huffman {
+        bytes: Vec<u8>,
+        code_lengths: Box<[u8; ALPHABET_SIZE]>,
+        extra_bits: Vec<u8>,
+    },
+    RangeCoder {
+        bytes: Vec<u8>,
+        symbol_byte_len: usize,
+        extra_bits: Vec<u8>,
+    },
+}
+
+fn zip_compress(data: &[u8], backend: EntropyBackend) -> ZipCompressed {
+    let lz77_encoded = lz77_encode(data, Lz77Options::default());
+    let TokenStream {
+        symbols,
+        extra_bits,
+    } = build_token_stream(&lz77_encoded);
+
+    match backend {
+        EntropyBackend::Huffman => {
+            // frequency for huffman tree
+            let mut frequencies: HashMap<u16, usize> = HashMap::new();
+            for &symbol in &symbols {
+                let counter = frequencies.entry(symbol).or_insert(0);
+                *counter += 1;
+            }
+
+            // huffman tree, reduced to a canonical code-length table so only
+            // the lengths (not a full codebook) need to be transmitted
+            let tree = build_tree(&frequencies);
+            let mut lengths = Vec::new();
+            code_lengths(&tree, 0, &mut lengths);
+            limit_lengths(&mut lengths, MAX_CODE_LENGTH);
+
+            let mut code_length_table = Box::new([0u8; ALPHABET_SIZE]);
+            for &(symbol, len) in &lengths {
+                code_length_table[symbol as usize] = len;
+            }
+            let codes = build_canonical_codes(&lengths);
+
+            // huffman encode, packed straight into bytes
+            let mut writer = BitWriter::new();
+            for &symbol in &symbols {
+                let (code, len) = codes.encode[&symbol];
+                writer.write_bits(code as usize, len);
+            }
+
+            ZipCompressed::Huffman {
+                bytes: writer.finish(),
+                code_lengths: code_length_table,
+                extra_bits,
+            }
+        }
+        EntropyBackend::RangeCoder => {
+            let mut symbol_bytes = Vec::with_capacity(symbols.len() * 2);
+            for &symbol in &symbols {
+                symbol_bytes.push((symbol >> 8) as u8);
+                symbol_bytes.push((symbol & 0xFF) as u8);
+            }
+
+            ZipCompressed::RangeCoder {
+                bytes: rangecoder_encode_bytes(&symbol_bytes),
+                symbol_byte_len: symbol_bytes.len(),
+                extra_bits,
+            }
+        }
+    }
+}
+
+fn read_symbol(reader: &mut BitReader, table: &HashMap<(u8, u32), u16>) -> u16 {
+    let mut code: u32 = 0;
+    let mut len: u8 = 0;
+    loop {
+        code = (code << 1) | reader.read_bit() as u32;
+        len += 1;
+        if let Some(&symbol) = table.get(&(len, code)) {
+            return symbol;
         }
     }
-    decoded
+}
+
+fn zip_decompress(compressed: &ZipCompressed) -> Vec<u8> {
+    let (symbols, extra_bits): (Vec<u16>, &[u8]) = match compressed {
+        ZipCompressed::Huffman {
+            bytes,
+            code_lengths,
+            extra_bits,
+        } => {
+            let lengths: Vec<(u16, u8)> = code_lengths
+                .iter()
+                .enumerate()
+                .filter(|&(_, &len)| len > 0)
+                .map(|(symbol, &len)| (symbol as u16, len))
+                .collect();
+            let codes = build_canonical_codes(&lengths);
+
+            let mut reader = BitReader::new(bytes);
+            let mut symbols = Vec::new();
+            loop {
+                let symbol = read_symbol(&mut reader, &codes.decode);
+                let done = symbol == END_OF_BLOCK;
+                symbols.push(symbol);
+                if done {
+                    break;
+                }
+            }
+            (symbols, extra_bits)
+        }
+        ZipCompressed::RangeCoder {
+            bytes,
+            symbol_byte_len,
+            extra_bits,
+        } => {
+            let symbol_bytes = rangecoder_decode_bytes(bytes, *symbol_byte_len);
+            let symbols = symbol_bytes
+                .chunks_exact(2)
+                .map(|pair| ((pair[0] as u16) << 8) | pair[1] as u16)
+                .collect();
+            (symbols, extra_bits.as_slice())
+        }
+    };
+
+    decode_token_stream(&symbols, extra_bits)
+}
+
+// Reported by Compressor/Decompressor after each call, telling the caller
+// what to do next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamStatus {
+    NeedsInput,
+    OutputFull,
+    Done,
+}
+
+// Streaming front end for the LZ77 pipeline: unlike `zip_compress`, which
+// needs the whole input up front, `Compressor` accepts arbitrary chunks and
+// writes coded bytes into a caller-supplied buffer.
+//
+// Deviation from EntropyBackend::Huffman: this only codes through the range
+// coder, never Huffman, because canonical Huffman needs frequencies over the
+// whole input before it can assign one code table, which an incremental API
+// can't offer. `zip_compress`/`zip_decompress` still support both backends
+// for the non-streaming case.
+//
+// `data` and the match finder retain at most 2 * WINDOW_SIZE already
+// tokenized bytes (see `trim`) plus any input not yet tokenized, so memory
+// does not grow with the total stream length.
+struct Compressor {
+    options: Lz77Options,
+    finder: MatchFinder,
+    data: Vec<u8>,
+    inserted: usize,
+    tokenized: usize,
+    model: rangecoder::SymbolModel,
+    encoder: rangecoder::Encoder,
+    out: VecDeque<u8>,
+    finished: bool,
+}
+
+impl Compressor {
+    fn new(options: Lz77Options) -> Self {
+        Compressor {
+            options,
+            finder: MatchFinder::new(0),
+            data: Vec::new(),
+            inserted: 0,
+            tokenized: 0,
+            model: rangecoder::SymbolModel::new(9),
+            encoder: rangecoder::Encoder::new(),
+            out: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    // Feeds `input` and drains as many coded bytes as fit into `output`.
+    fn write(&mut self, input: &[u8], output: &mut [u8]) -> (usize, usize, StreamStatus) {
+        self.data.extend_from_slice(input);
+        self.tokenize(false);
+        self.trim();
+        let (produced, status) = self.drain(output);
+        (input.len(), produced, status)
+    }
+
+    // Already-tokenized bytes more than WINDOW_SIZE behind the current
+    // position can never be matched against again, so they're dropped from
+    // `data` (and the positions they occupied remapped out of `finder`)
+    // instead of being retained for the rest of the stream. Only acts once
+    // there's a full window of slack, so this is amortized O(1) per byte
+    // rather than shifting on every call.
+    fn trim(&mut self) {
+        let window_start = self.tokenized.saturating_sub(WINDOW_SIZE);
+        if window_start > WINDOW_SIZE {
+            self.data.drain(0..window_start);
+            self.finder.shift(window_start);
+            self.tokenized -= window_start;
+            self.inserted -= window_start;
+        }
+    }
+
+    // Signals no more input is coming; call repeatedly until it reports Done.
+    fn finish(&mut self, output: &mut [u8]) -> (usize, StreamStatus) {
+        if !self.finished {
+            self.tokenize(true);
+            self.emit_symbol(END_OF_BLOCK);
+            let encoder = std::mem::replace(&mut self.encoder, rangecoder::Encoder::new());
+            self.out.extend(encoder.finish());
+            self.finished = true;
+        }
+        self.drain(output)
+    }
+
+    fn tokenize(&mut self, flush: bool) {
+        loop {
+            let i = self.tokenized;
+            if i >= self.data.len() {
+                break;
+            }
+            // Can't yet tell whether a match found here might grow with
+            // more input, or whether the lazy one-ahead peek below would
+            // prefer position i+1 instead - wait for more data unless this
+            // is the final flush.
+            if !flush && i + 1 >= self.data.len() {
+                break;
+            }
+
+            let data = &self.data;
+            insert_before(&mut self.finder, data, &mut self.inserted, i);
+            let (offset, length) = self.finder.find_longest_match(data, i, self.options.probe_max);
+
+            if length < MIN_MATCH {
+                self.emit_symbol(self.data[i] as u16);
+                self.tokenized = i + 1;
+                continue;
+            }
+
+            // The match runs right up to the edge of what's been fed so
+            // far: more input could extend it further, so hold off
+            // finalizing this token (see the comment above).
+            if !flush && i + length >= self.data.len() && length < WINDOW_SIZE {
+                break;
+            }
+
+            let data = &self.data;
+            insert_before(&mut self.finder, data, &mut self.inserted, i + 1);
+
+            if self.options.lazy_match && i + 1 < self.data.len() {
+                let data = &self.data;
+                let (_, next_length) =
+                    self.finder
+                        .find_longest_match(data, i + 1, self.options.probe_max);
+                if next_length > length {
+                    self.emit_symbol(self.data[i] as u16);
+                    self.tokenized = i + 1;
+                    continue;
+                }
+            }
+
+            let data = &self.data;
+            insert_before(&mut self.finder, data, &mut self.inserted, i + length);
+            self.emit_match(offset, length);
+            self.tokenized = i + length;
+        }
+    }
+
+    fn emit_symbol(&mut self, symbol: u16) {
+        self.model.encode(&mut self.encoder, symbol);
+        self.out.extend(self.encoder.drain_output());
+    }
+
+    fn emit_match(&mut self, offset: usize, length: usize) {
+        let (length_extra_bits, length_base) = bucket(length);
+        let (dist_code, dist_base) = bucket(offset);
+
+        self.model
+            .encode(&mut self.encoder, LENGTH_SYMBOL_BASE + length_extra_bits as u16);
+        self.encoder
+            .encode_bits_bypass(length - length_base, length_extra_bits);
+        self.encoder
+            .encode_bits_bypass(dist_code as usize, DIST_CODE_BITS);
+        self.encoder
+            .encode_bits_bypass(offset - dist_base, dist_code);
+
+        self.out.extend(self.encoder.drain_output());
+    }
+
+    fn drain(&mut self, output: &mut [u8]) -> (usize, StreamStatus) {
+        let produced = output.len().min(self.out.len());
+        for slot in output.iter_mut().take(produced) {
+            *slot = self.out.pop_front().unwrap();
+        }
+        let status = if !self.out.is_empty() {
+            StreamStatus::OutputFull
+        } else if self.finished {
+            StreamStatus::Done
+        } else {
+            StreamStatus::NeedsInput
+        };
+        (produced, status)
+    }
+}
+
+// A normalize() call inside decode_bit/decode_bits_bypass can consume a
+// handful of bytes in the worst case; keeping at least this many bytes
+// buffered ahead before decoding the next symbol means the decoder never
+// has to fall back to a phantom zero byte before the stream has legitimately
+// ended (that fallback is only safe once `finish` has been called, the same
+// way the encoder's flush at the very end relies on it).
+const DECODE_MARGIN: usize = 16;
+
+// Mirrors Compressor. Keeps only the last WINDOW_SIZE bytes of produced
+// output around (in `history`), since that's all an LZ77 back-reference
+// can ever need.
+struct Decompressor {
+    model: rangecoder::SymbolModel,
+    decoder: rangecoder::Decoder,
+    history: VecDeque<u8>,
+    out: VecDeque<u8>,
+    done: bool,
+}
+
+impl Decompressor {
+    fn new() -> Self {
+        Decompressor {
+            model: rangecoder::SymbolModel::new(9),
+            decoder: rangecoder::Decoder::new(&[]),
+            history: VecDeque::new(),
+            out: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    fn write(&mut self, input: &[u8], output: &mut [u8]) -> (usize, usize, StreamStatus) {
+        self.decoder.feed(input);
+        self.decode_available(false);
+        let (produced, status) = self.drain(output);
+        (input.len(), produced, status)
+    }
+
+    // Signals no more input is coming; call repeatedly until it reports Done.
+    fn finish(&mut self, output: &mut [u8]) -> (usize, StreamStatus) {
+        self.decode_available(true);
+        self.drain(output)
+    }
+
+    fn decode_available(&mut self, flush: bool) {
+        while !self.done && (flush || self.decoder.unread_len() >= DECODE_MARGIN) {
+            let symbol = self.model.decode(&mut self.decoder);
+            if symbol == END_OF_BLOCK {
+                self.done = true;
+            } else if symbol < LENGTH_SYMBOL_BASE {
+                self.push_byte(symbol as u8);
+            } else {
+                let length_extra_bits = (symbol - LENGTH_SYMBOL_BASE) as u8;
+                let length =
+                    (1usize << length_extra_bits) + self.decoder.decode_bits_bypass(length_extra_bits);
+
+                let dist_code = self.decoder.decode_bits_bypass(DIST_CODE_BITS) as u8;
+                let offset = (1usize << dist_code) + self.decoder.decode_bits_bypass(dist_code);
+
+                for _ in 0..length {
+                    let byte = self.history[self.history.len() - offset];
+                    self.push_byte(byte);
+                }
+            }
+        }
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        self.history.push_back(byte);
+        if self.history.len() > WINDOW_SIZE {
+            self.history.pop_front();
+        }
+        self.out.push_back(byte);
+    }
+
+    fn drain(&mut self, output: &mut [u8]) -> (usize, StreamStatus) {
+        let produced = output.len().min(self.out.len());
+        for slot in output.iter_mut().take(produced) {
+            *slot = self.out.pop_front().unwrap();
+        }
+        let status = if !self.out.is_empty() {
+            StreamStatus::OutputFull
+        } else if self.done {
+            StreamStatus::Done
+        } else {
+            StreamStatus::NeedsInput
+        };
+        (produced, status)
+    }
+}
+
+// Tuning knobs for block-parallel compression.
+#[derive(Debug, Clone, Copy)]
+struct Options {
+    // Target size of each independently-compressed block, in bytes.
+    block_size: usize,
+    // How many worker threads to split the blocks across.
+    thread_count: usize,
+    // Whether a block may end early - before `block_size` is reached - once
+    // its trailing bytes stop resembling the rest of the block.
+    dynamic_block_size: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            block_size: 4096,
+            thread_count: 4,
+            dynamic_block_size: false,
+        }
+    }
+}
+
+// How much of a block's tail to sample when deciding whether the local
+// statistics have shifted enough to cut a `dynamic_block_size` block short.
+const DYNAMIC_SAMPLE: usize = 64;
+// Never cut a dynamic block shorter than this, so tiny early samples can't
+// cause needlessly small blocks.
+const DYNAMIC_MIN_BLOCK: usize = 256;
+
+// Splits `data` into block byte ranges, each compressed independently.
+fn split_blocks(data: &[u8], options: &Options) -> Vec<(usize, usize)> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+
+    while start < data.len() {
+        let max_end = (start + options.block_size).min(data.len());
+        let end = if options.dynamic_block_size {
+            dynamic_block_end(data, start, max_end)
+        } else {
+            max_end
+        };
+        blocks.push((start, end - start));
+        start = end;
+    }
+
+    blocks
+}
+
+// Scans forward from `start + DYNAMIC_MIN_BLOCK`, comparing each
+// `DYNAMIC_SAMPLE`-byte window's byte-value distribution against the
+// distribution of everything accumulated in the block so far. A large gap
+// between the two (measured as the sum of absolute differences, 0 = identical,
+// 2 = disjoint) means the input has moved on to a different kind of data, so the
+// block is cut there instead of carrying on to `max_end` with a byte
+// frequency table that no longer fits what follows.
+fn dynamic_block_end(data: &[u8], start: usize, max_end: usize) -> usize {
+    if max_end - start < DYNAMIC_MIN_BLOCK + DYNAMIC_SAMPLE {
+        return max_end;
+    }
+
+    let mut freq = [0u32; 256];
+    for &byte in &data[start..start + DYNAMIC_MIN_BLOCK] {
+        freq[byte as usize] += 1;
+    }
+    let mut accumulated = DYNAMIC_MIN_BLOCK;
+
+    let mut pos = start + DYNAMIC_MIN_BLOCK;
+    while pos + DYNAMIC_SAMPLE <= max_end {
+        let sample = &data[pos..pos + DYNAMIC_SAMPLE];
+        let mut sample_freq = [0u32; 256];
+        for &byte in sample {
+            sample_freq[byte as usize] += 1;
+        }
+
+        let mut divergence = 0.0;
+        for i in 0..256 {
+            let block_p = freq[i] as f64 / accumulated as f64;
+            let sample_p = sample_freq[i] as f64 / DYNAMIC_SAMPLE as f64;
+            divergence += (block_p - sample_p).abs();
+        }
+        if divergence > 1.0 {
+            return pos;
+        }
+
+        for &byte in sample {
+            freq[byte as usize] += 1;
+        }
+        accumulated += DYNAMIC_SAMPLE;
+        pos += DYNAMIC_SAMPLE;
+    }
+
+    max_end
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn read_u32(data: &[u8], pos: &mut usize) -> u32 {
+    let value = u32::from_be_bytes(data[*pos..*pos + 4].try_into().unwrap());
+    *pos += 4;
+    value
+}
+
+// Flattens a block's `ZipCompressed` payload to bytes, so independently
+// -compressed blocks can be concatenated into one stream: the block's
+// original length as a u32 (needed to size nothing here, but handy for
+// callers inspecting the stream), a one-byte backend tag, then each field of the
+// matching `ZipCompressed` variant, each length-prefixed.
+fn serialize_block(original_len: usize, compressed: &ZipCompressed) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_u32(&mut out, original_len as u32);
+
+    match compressed {
+        ZipCompressed::Huffman {
+            bytes,
+            code_lengths,
+            extra_bits,
+        } => {
+            out.push(0);
+            out.extend_from_slice(code_lengths.as_slice());
+            write_u32(&mut out, extra_bits.len() as u32);
+            out.extend_from_slice(extra_bits);
+            write_u32(&mut out, bytes.len() as u32);
+            out.extend_from_slice(bytes);
+        }
+        ZipCompressed::RangeCoder {
+            bytes,
+            symbol_byte_len,
+            extra_bits,
+        } => {
+            out.push(1);
+            write_u32(&mut out, *symbol_byte_len as u32);
+            write_u32(&mut out, extra_bits.len() as u32);
+            out.extend_from_slice(extra_bits);
+            write_u32(&mut out, bytes.len() as u32);
+            out.extend_from_slice(bytes);
+        }
+    }
+
+    out
+}
+
+fn deserialize_block(data: &[u8]) -> ZipCompressed {
+    let mut pos = 0;
+    let _original_len = read_u32(data, &mut pos);
+    let tag = data[pos];
+    pos += 1;
+
+    match tag {
+        0 => {
+            let mut code_lengths = Box::new([0u8; ALPHABET_SIZE]);
+            code_lengths.copy_from_slice(&data[pos..pos + ALPHABET_SIZE]);
+            pos += ALPHABET_SIZE;
+            let extra_len = read_u32(data, &mut pos) as usize;
+            let extra_bits = data[pos..pos + extra_len].to_vec();
+            pos += extra_len;
+            let bytes_len = read_u32(data, &mut pos) as usize;
+            let bytes = data[pos..pos + bytes_len].to_vec();
+            ZipCompressed::Huffman {
+                bytes,
+                code_lengths,
+                extra_bits,
+            }
+        }
+        1 => {
+            let symbol_byte_len = read_u32(data, &mut pos) as usize;
+            let extra_len = read_u32(data, &mut pos) as usize;
+            let extra_bits = data[pos..pos + extra_len].to_vec();
+            pos += extra_len;
+            let bytes_len = read_u32(data, &mut pos) as usize;
+            let bytes = data[pos..pos + bytes_len].to_vec();
+            ZipCompressed::RangeCoder {
+                bytes,
+                symbol_byte_len,
+                extra_bits,
+            }
+        }
+        other => panic!("unknown block backend tag: {}", other),
+    }
+}
+
+// Hands block indices out to worker threads one at a time, so threads that
+// finish an easy (e.g. highly repetitive) block early pick up more work
+// instead of sitting idle while another thread churns through a harder one.
+fn next_work_item(cursor: &Mutex<usize>, block_count: usize) -> Option<usize> {
+    let mut next = cursor.lock().unwrap();
+    if *next >= block_count {
+        return None;
+    }
+    let index = *next;
+    *next += 1;
+    Some(index)
+}
+
+// Compresses `data` as independent blocks spread across
+// `options.thread_count` worker threads, framed as [u32 frame_len][frame...].
+fn parallel_compress(data: &[u8], backend: EntropyBackend, options: Options) -> Vec<u8> {
+    let block_ranges = split_blocks(data, &options);
+    let results: Vec<Mutex<Option<Vec<u8>>>> = block_ranges.iter().map(|_| Mutex::new(None)).collect();
+    let cursor = Mutex::new(0usize);
+    let thread_count = options.thread_count.max(1).min(block_ranges.len().max(1));
+
+    std::thread::scope(|scope| {
+        for _ in 0..thread_count {
+            scope.spawn(|| {
+                while let Some(index) = next_work_item(&cursor, block_ranges.len()) {
+                    let (start, len) = block_ranges[index];
+                    let compressed = zip_compress(&data[start..start + len], backend);
+                    *results[index].lock().unwrap() = Some(serialize_block(len, &compressed));
+                }
+            });
+        }
+    });
+
+    let mut out = Vec::new();
+    for result in results {
+        let frame = result.into_inner().unwrap().expect("every block was compressed");
+        write_u32(&mut out, frame.len() as u32);
+        out.extend_from_slice(&frame);
+    }
+    out
+}
+
+// Reverses parallel_compress: splits the framed stream back into blocks,
+// then decompresses them across worker threads and concatenates in order.
+fn parallel_decompress(data: &[u8], options: Options) -> Vec<u8> {
+    let mut frames = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let frame_len = read_u32(data, &mut pos) as usize;
+        frames.push(&data[pos..pos + frame_len]);
+        pos += frame_len;
+    }
+
+    let results: Vec<Mutex<Option<Vec<u8>>>> = frames.iter().map(|_| Mutex::new(None)).collect();
+    let cursor = Mutex::new(0usize);
+    let thread_count = options.thread_count.max(1).min(frames.len().max(1));
+
+    std::thread::scope(|scope| {
+        for _ in 0..thread_count {
+            scope.spawn(|| {
+                while let Some(index) = next_work_item(&cursor, frames.len()) {
+                    let compressed = deserialize_block(frames[index]);
+                    *results[index].lock().unwrap() = Some(zip_decompress(&compressed));
+                }
+            });
+        }
+    });
+
+    let mut out = Vec::new();
+    for result in results {
+        out.extend(result.into_inner().unwrap().expect("every block was decompressed"));
+    }
+    out
+}
+
+// Regression check for `limit_lengths`: a Fibonacci frequency table is the
+// classic Huffman worst case (each added symbol pushes the deepest leaf
+// one level further down), so it reliably builds trees far past MAX_CODE_LENGTH and
+// exercises the length-limiting step the "ABRACADABRACADABRA" smoke test
+// below never gets close to triggering.
+fn check_length_limiting() {
+    let mut frequencies: HashMap<u16, usize> = HashMap::new();
+    let (mut a, mut b) = (1usize, 1usize);
+    for symbol in 0..40u16 {
+        frequencies.insert(symbol, a);
+        let next = a + b;
+        a = b;
+        b = next;
+    }
+
+    let tree = build_tree(&frequencies);
+    let mut lengths = Vec::new();
+    code_lengths(&tree, 0, &mut lengths);
+    limit_lengths(&mut lengths, MAX_CODE_LENGTH);
+
+    assert!(lengths.iter().all(|&(_, len)| len <= MAX_CODE_LENGTH));
+    let kraft_sum: f64 = lengths
+        .iter()
+        .map(|&(_, len)| 2f64.powi(-(len as i32)))
+        .sum();
+    assert!(kraft_sum <= 1.0, "Kraft inequality violated: {kraft_sum}");
+
+    let codes = build_canonical_codes(&lengths);
+    for &(symbol, len) in &lengths {
+        let (code, encoded_len) = codes.encode[&symbol];
+        assert_eq!(encoded_len, len);
+        assert_eq!(codes.decode[&(len, code)], symbol);
+    }
 }
 
 fn main() {
+    check_length_limiting();
+
     let input = "ABRACADABRACADABRA".as_bytes().to_vec();
-    let (compressed, codebook) = zip_compress(&input);
-    let decompressed = zip_decompress(&compressed, &codebook);
-    assert_eq!(input, decompressed);
+
+    let huffman_compressed = zip_compress(&input, EntropyBackend::Huffman);
+    let huffman_decompressed = zip_decompress(&huffman_compressed);
+    assert_eq!(input, huffman_decompressed);
+    if let ZipCompressed::Huffman { bytes, .. } = &huffman_compressed {
+        println!("Huffman compressed size: {} bytes", bytes.len());
+    }
+
+    let rc_compressed = zip_compress(&input, EntropyBackend::RangeCoder);
+    let rc_decompressed = zip_decompress(&rc_compressed);
+    assert_eq!(input, rc_decompressed);
+    if let ZipCompressed::RangeCoder { bytes, .. } = &rc_compressed {
+        println!("Range coder compressed size: {} bytes", bytes.len());
+    }
 
     println!("Original: {:?}", input);
-    println!("Compressed: {:?}", compressed);
-    println!("Decompressed: {:?}", decompressed);
     println!("Original size: {} bits", input.len() * 8);
-    println!("Compressed size: {} bits", compressed.len());
+
+    // Feed the streaming pipeline a handful of bytes at a time, draining
+    // through a small output buffer, to exercise the chunk boundaries the
+    // batch API doesn't have to deal with.
+    let mut compressor = Compressor::new(Lz77Options::default());
+    let mut coded = Vec::new();
+    let mut scratch = [0u8; 4];
+    for chunk in input.chunks(3) {
+        let mut offset = 0;
+        loop {
+            let (consumed, produced, status) = compressor.write(&chunk[offset..], &mut scratch);
+            coded.extend_from_slice(&scratch[..produced]);
+            offset += consumed;
+            if status != StreamStatus::OutputFull {
+                break;
+            }
+        }
+    }
+    loop {
+        let (produced, status) = compressor.finish(&mut scratch);
+        coded.extend_from_slice(&scratch[..produced]);
+        if status == StreamStatus::Done {
+            break;
+        }
+    }
+
+    let mut decompressor = Decompressor::new();
+    let mut streamed = Vec::new();
+    for chunk in coded.chunks(3) {
+        let mut offset = 0;
+        loop {
+            let (consumed, produced, status) = decompressor.write(&chunk[offset..], &mut scratch);
+            streamed.extend_from_slice(&scratch[..produced]);
+            offset += consumed;
+            if status != StreamStatus::OutputFull {
+                break;
+            }
+        }
+    }
+    loop {
+        let (produced, status) = decompressor.finish(&mut scratch);
+        streamed.extend_from_slice(&scratch[..produced]);
+        if status == StreamStatus::Done {
+            break;
+        }
+    }
+
+    assert_eq!(input, streamed);
+    println!("Streaming compressed size: {} bytes", coded.len());
+
+    // Compress a larger, more repetitive input as several blocks spread
+    // across worker threads, each with its own LZ77 window and entropy
+    // table, and confirm the framed stream decompresses back in parallel
+    // to the original.
+    let block_input: Vec<u8> = "ABRACADABRA".repeat(50).into_bytes();
+    let block_options = Options {
+        block_size: 128,
+        thread_count: 4,
+        dynamic_block_size: true,
+    };
+    let block_compressed = parallel_compress(&block_input, EntropyBackend::Huffman, block_options);
+    let block_decompressed = parallel_decompress(&block_compressed, block_options);
+    assert_eq!(block_input, block_decompressed);
+    println!(
+        "Block-parallel compressed size: {} bytes ({} blocks)",
+        block_compressed.len(),
+        split_blocks(&block_input, &block_options).len()
+    );
 }